@@ -0,0 +1,104 @@
+//! Parses the command line arguments passed to `cargo grubimage`.
+
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+/// The parsed `cargo grubimage` subcommand.
+pub enum BuildCommand {
+    /// Builds the kernel and wraps it in a bootable GRUB disk image.
+    Build(BuildArgs),
+    /// Builds the kernel, wraps it in a disk image, and runs it in a VM via `run-command`.
+    Run(BuildArgs),
+    /// Wraps a single already-built kernel executable in a disk image and runs it.
+    ///
+    /// This is the entry point used by the `target.*.runner` setting in `.cargo/config.toml`,
+    /// which invokes `cargo grubimage runner <executable>` with the path to the kernel
+    /// executable that `cargo run`/`cargo test` just built.
+    Runner(PathBuf),
+    /// Builds and runs every test binary of the kernel crate.
+    Test(BuildArgs),
+    /// Prints the crate version.
+    Version,
+    /// Prints usage information.
+    Help,
+}
+
+/// Arguments shared by the `build`, `run`, and `test` subcommands.
+#[derive(Debug, Default)]
+pub struct BuildArgs {
+    manifest_path: Option<String>,
+    quiet: bool,
+    release: bool,
+    cargo_args: Vec<String>,
+}
+
+impl BuildArgs {
+    /// The path to the Cargo.toml of the kernel, if explicitly given via `--manifest-path`.
+    pub fn manifest_path(&self) -> Option<&str> {
+        self.manifest_path.as_deref()
+    }
+
+    /// Whether output to stdout should be suppressed.
+    pub fn quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// Whether the kernel should be built in release mode.
+    pub fn release(&self) -> bool {
+        self.release
+    }
+
+    /// The arguments forwarded as-is to the underlying `cargo build` invocation: everything
+    /// except `-q`/`--quiet`, including `--release` and `--manifest-path <PATH>`.
+    pub fn cargo_args(&self) -> &[String] {
+        &self.cargo_args
+    }
+}
+
+impl BuildCommand {
+    /// Parses the arguments following `cargo grubimage` into a [`BuildCommand`].
+    pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        match args.next() {
+            Some(arg) if arg == "--version" || arg == "-V" => Ok(BuildCommand::Version),
+            Some(arg) if arg == "--help" || arg == "-h" => Ok(BuildCommand::Help),
+            Some(arg) if arg == "run" => Ok(BuildCommand::Run(parse_build_args(args)?)),
+            Some(arg) if arg == "test" => Ok(BuildCommand::Test(parse_build_args(args)?)),
+            Some(arg) if arg == "runner" => {
+                let executable = args.next().ok_or_else(|| {
+                    anyhow!("`cargo grubimage runner` expects the path to a kernel executable")
+                })?;
+                Ok(BuildCommand::Runner(PathBuf::from(executable)))
+            }
+            Some(arg) if arg == "build" => Ok(BuildCommand::Build(parse_build_args(args)?)),
+            // No recognized subcommand: treat the whole argument list as `cargo build` options,
+            // so e.g. `cargo grubimage --release` still builds.
+            Some(arg) => Ok(BuildCommand::Build(parse_build_args(
+                std::iter::once(arg).chain(args),
+            )?)),
+            None => Ok(BuildCommand::Build(BuildArgs::default())),
+        }
+    }
+}
+
+fn parse_build_args(mut args: impl Iterator<Item = String>) -> Result<BuildArgs> {
+    let mut build_args = BuildArgs::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-q" | "--quiet" => build_args.quiet = true,
+            "--release" => {
+                build_args.release = true;
+                build_args.cargo_args.push(arg);
+            }
+            "--manifest-path" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--manifest-path expects an argument"))?;
+                build_args.cargo_args.push(arg);
+                build_args.cargo_args.push(path.clone());
+                build_args.manifest_path = Some(path);
+            }
+            _ => build_args.cargo_args.push(arg),
+        }
+    }
+    Ok(build_args)
+}
@@ -1,12 +1,14 @@
 use anyhow::{anyhow, Context, Result};
 use grubimage::{
     args::{BuildArgs, BuildCommand},
-    builder::Builder,
-    config, help,
+    builder::{run_image, run_image_profiles, test_runner::TestStatus, Builder},
+    config::{self, Config},
+    help,
 };
 use std::{
     env,
     path::{Path, PathBuf},
+    process,
 };
 
 pub fn main() -> Result<()> {
@@ -29,7 +31,10 @@ pub fn main() -> Result<()> {
     }
 
     match BuildCommand::parse_args(raw_args)? {
-        BuildCommand::Build(args) => build(args),
+        BuildCommand::Build(args) => build(args).map(|_| ()),
+        BuildCommand::Run(args) => run(args),
+        BuildCommand::Runner(executable) => runner(executable),
+        BuildCommand::Test(args) => test(args),
         BuildCommand::Version => {
             help::print_version();
             Ok(())
@@ -41,63 +46,206 @@ pub fn main() -> Result<()> {
     }
 }
 
-fn build(args: BuildArgs) -> Result<()> {
+/// Builds the kernel and wraps every resulting executable in a grubimage.
+///
+/// Returns the paths to the created grubimages, alongside the config that was used to build
+/// them, for use by `run` and `test`.
+fn build(args: BuildArgs) -> Result<(Vec<PathBuf>, Config)> {
     let mut builder = Builder::new(args.manifest_path().map(PathBuf::from))?;
     let config = config::read_config(builder.manifest_path())?;
     let quiet = args.quiet();
 
-    let executables = builder.build_kernel(&args.cargo_args(), &config, quiet)?;
+    let executables = builder.build_kernel(args.cargo_args(), &config, quiet)?;
     if executables.is_empty() {
         return Err(anyhow!("no executables built"));
     }
 
+    let mut grubimages = Vec::new();
     for executable in executables {
-        let out_dir = executable
-            .parent()
-            .ok_or_else(|| anyhow!("executable has no parent path"))?;
-        let bin_name = &executable
-            .file_stem()
-            .ok_or_else(|| anyhow!("executable has no file stem"))?
-            .to_str()
-            .ok_or_else(|| anyhow!("executable file stem not valid utf8"))?;
-
-        let iso_files = out_dir.join("isofiles");
-        // We don't have access to a CARGO_MANIFEST_DIR environment variable
-        // here because `cargo grubimage` is started directly by the user. We
-        // therefore have to find out the path to the Cargo.toml of the
-        // executables ourselves. For workspace projects, this can be a
-        // different Cargo.toml than the Cargo.toml in the current directory.
-        //
-        // To retrieve the correct Cargo.toml path, we look for the binary name
-        // in the `cargo metadata` output and then get the manifest path from
-        // the corresponding package.
-        let kernel_package = builder
-            .kernel_package_for_bin(bin_name)
-            .context("Failed to run cargo metadata to find out kernel manifest path")?
-            .ok_or_else(|| anyhow!("Failed to find kernel binary in cargo metadata output"))?;
-        let kernel_manifest_path = &kernel_package.manifest_path.to_owned();
-
-        let grubimage_path = out_dir.join(format!("grubimage-{}.iso", bin_name));
-
-        let grubimage = grubimage::builder::Grubimage {
-            kernel_manifest: &kernel_manifest_path,
-            bin_path: &executable,
-            output_bin_path: &grubimage_path,
-            quiet,
-            release: args.release(),
-            iso_dir_path: &iso_files,
-            bin_name: &bin_name,
-        };
-
-        builder.create_grubimage(&grubimage)?;
-        if !args.quiet() {
+        let grubimage_path =
+            wrap_grubimage(&mut builder, &executable, quiet, args.release(), &config)?;
+        if !quiet {
             println!(
                 "Created grubimage for `{}` at `{}`",
-                bin_name,
+                executable.display(),
                 grubimage_path.display()
             );
         }
+        grubimages.push(grubimage_path);
+    }
+
+    Ok((grubimages, config))
+}
+
+/// Builds the kernel, wraps it in a grubimage, and runs it through `run-command` (once per
+/// configured `package.metadata.grubimage` profile).
+fn run(args: BuildArgs) -> Result<()> {
+    let quiet = args.quiet();
+    let (grubimages, config) = build(args)?;
+
+    for grubimage_path in grubimages {
+        let extra_args = config.run_args.clone().unwrap_or_default();
+        if !quiet {
+            println!("Running `{}`", grubimage_path.display());
+        }
+        let mut failed = false;
+        for (profile, status) in run_image_profiles(&grubimage_path, &config, &extra_args)? {
+            if !status.success() {
+                eprintln!("[{}] `{}` failed: {}", profile, grubimage_path.display(), status);
+                failed = true;
+            }
+        }
+        if failed {
+            return Err(anyhow!("`{}` failed", grubimage_path.display()));
+        }
     }
 
     Ok(())
 }
+
+/// The `target.*.runner` entry point: wraps a single already-built kernel executable in a
+/// grubimage and runs it.
+///
+/// For a regular binary, the child's exit code is forwarded. For a test binary, the process
+/// exits with zero if every profile passed, and with a nonzero code otherwise.
+fn runner(executable: PathBuf) -> Result<()> {
+    let mut builder = Builder::new(None)?;
+    let config = config::read_config(builder.manifest_path())?;
+
+    let grubimage_path = wrap_grubimage(&mut builder, &executable, false, false, &config)?;
+
+    // Test binaries live in `target/<profile>/deps/`, while regular binaries live directly in
+    // `target/<profile>/`. We use that to decide which set of extra runner arguments applies.
+    let is_test_binary = executable
+        .parent()
+        .and_then(|p| p.file_name())
+        .is_some_and(|name| name == "deps");
+
+    if is_test_binary {
+        let extra_args = config.test_args.clone().unwrap_or_default();
+        let results = builder.run_test_image(&grubimage_path, &config, &extra_args)?;
+        let mut exit_code = 0;
+        for (profile, status) in results {
+            exit_code = match status {
+                TestStatus::Success => exit_code,
+                TestStatus::Failed(status) => match status.code() {
+                    Some(0) | None => 1,
+                    Some(code) => code,
+                },
+                TestStatus::TimedOut => {
+                    eprintln!(
+                        "[{}] Test timed out after {} seconds",
+                        profile, config.test_timeout
+                    );
+                    1
+                }
+            };
+        }
+        process::exit(exit_code);
+    }
+
+    let extra_args = config.run_args.clone().unwrap_or_default();
+    let status = run_image(&grubimage_path, &config, &extra_args)?;
+    process::exit(status.code().unwrap_or(1));
+}
+
+/// Builds every test binary of the kernel crate, wraps each in a grubimage, and runs it.
+fn test(args: BuildArgs) -> Result<()> {
+    let mut builder = Builder::new(args.manifest_path().map(PathBuf::from))?;
+    let config = config::read_config(builder.manifest_path())?;
+    let quiet = args.quiet();
+
+    let executables = builder.build_test_kernels(args.cargo_args(), &config, quiet)?;
+    if executables.is_empty() {
+        println!("No tests found");
+        return Ok(());
+    }
+
+    let mut failed = false;
+    let test_args = config.test_args.clone().unwrap_or_default();
+    for executable in executables {
+        let bin_name = executable
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        println!("Running test `{}`", bin_name);
+
+        let grubimage_path =
+            wrap_grubimage(&mut builder, &executable, quiet, args.release(), &config)?;
+        for (profile, status) in builder.run_test_image(&grubimage_path, &config, &test_args)? {
+            match status {
+                TestStatus::Success => println!("Test `{}` [{}] passed", bin_name, profile),
+                TestStatus::Failed(status) => {
+                    eprintln!("Test `{}` [{}] failed: {}", bin_name, profile, status);
+                    failed = true;
+                }
+                TestStatus::TimedOut => {
+                    eprintln!(
+                        "Test `{}` [{}] timed out after {} seconds",
+                        bin_name, profile, config.test_timeout
+                    );
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    if failed {
+        return Err(anyhow!("one or more tests failed"));
+    }
+
+    Ok(())
+}
+
+/// Wraps the given kernel executable in a grubimage and returns the path to the created image.
+fn wrap_grubimage(
+    builder: &mut Builder,
+    executable: &Path,
+    quiet: bool,
+    release: bool,
+    config: &Config,
+) -> Result<PathBuf> {
+    let out_dir = executable
+        .parent()
+        .ok_or_else(|| anyhow!("executable has no parent path"))?;
+    let bin_name = &executable
+        .file_stem()
+        .ok_or_else(|| anyhow!("executable has no file stem"))?
+        .to_str()
+        .ok_or_else(|| anyhow!("executable file stem not valid utf8"))?;
+
+    let iso_files = out_dir.join("isofiles");
+    // We don't have access to a CARGO_MANIFEST_DIR environment variable
+    // here because `cargo grubimage` is started directly by the user. We
+    // therefore have to find out the path to the Cargo.toml of the
+    // executables ourselves. For workspace projects, this can be a
+    // different Cargo.toml than the Cargo.toml in the current directory.
+    //
+    // To retrieve the correct Cargo.toml path, we look for the binary name
+    // in the `cargo metadata` output and then get the manifest path from
+    // the corresponding package. Test binaries are named `<target>-<hash>` and have no `bin`
+    // target of that name, so for them we fall back to the manifest of the project being built.
+    let kernel_manifest_path = builder
+        .kernel_package_for_bin(bin_name)
+        .context("Failed to run cargo metadata to find out kernel manifest path")?
+        .map(|kernel_package| kernel_package.manifest_path.clone().into_std_path_buf());
+    let kernel_manifest_path =
+        kernel_manifest_path.unwrap_or_else(|| builder.manifest_path().to_owned());
+
+    let grubimage_path = out_dir.join(format!("grubimage-{}.iso", bin_name));
+
+    let grubimage = grubimage::builder::Grubimage {
+        kernel_manifest: &kernel_manifest_path,
+        bin_path: executable,
+        output_bin_path: &grubimage_path,
+        quiet,
+        release,
+        iso_dir_path: &iso_files,
+        bin_name,
+        grub_config: &config.grub,
+    };
+
+    builder.create_grubimage(&grubimage)?;
+
+    Ok(grubimage_path)
+}
@@ -0,0 +1,160 @@
+//! Assembles a bootable GRUB disk image around a kernel executable.
+
+use super::error::CreategrubimageError;
+use crate::config::{GrubConfig, MultibootVersion};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Creates a bootable GRUB disk image at `output_bin_path` that boots `bin_path`.
+///
+/// `iso_dir_path` is used as scratch space for the ISO file tree (`boot/grub/grub.cfg` and the
+/// kernel binary) that is passed to `grub-mkrescue`. `bin_name` is used as the on-disk kernel
+/// file name, and, unless `grub_config.grub_cfg` overrides the whole template, as the title of
+/// the generated GRUB menu entry. `kernel_manifest` is the kernel's `Cargo.toml` path, used to
+/// resolve `grub_config.grub_cfg` relative to the kernel crate rather than the process's cwd.
+pub fn create_iso_image(
+    output_bin_path: &Path,
+    iso_dir_path: &Path,
+    bin_path: &Path,
+    bin_name: &str,
+    grub_config: &GrubConfig,
+    kernel_manifest: &Path,
+) -> Result<(), CreategrubimageError> {
+    let grub_dir = iso_dir_path.join("boot").join("grub");
+    fs::create_dir_all(&grub_dir).map_err(|error| CreategrubimageError::Io {
+        message: "failed to create isofiles/boot/grub directory",
+        error,
+    })?;
+
+    let kernel_dest: PathBuf = iso_dir_path.join("boot").join(bin_name);
+    fs::copy(bin_path, &kernel_dest).map_err(|error| CreategrubimageError::Io {
+        message: "failed to copy kernel executable into isofiles/boot",
+        error,
+    })?;
+
+    let grub_cfg = build_grub_cfg(grub_config, bin_name, kernel_manifest)?;
+    fs::write(grub_dir.join("grub.cfg"), grub_cfg).map_err(|error| CreategrubimageError::Io {
+        message: "failed to write isofiles/boot/grub/grub.cfg",
+        error,
+    })?;
+
+    let output = process::Command::new("grub-mkrescue")
+        .arg("-o")
+        .arg(output_bin_path)
+        .arg(iso_dir_path)
+        .output()
+        .map_err(|error| CreategrubimageError::Io {
+            message: "failed to execute `grub-mkrescue`",
+            error,
+        })?;
+    if !output.status.success() {
+        return Err(CreategrubimageError::GrubMkrescueFailed {
+            stderr: output.stderr,
+        });
+    }
+
+    Ok(())
+}
+
+/// Builds the `grub.cfg` contents for the disk image.
+///
+/// If `grub_config.grub_cfg` points at a user-supplied template, it is used as-is with the
+/// `{kernel}`/`{name}` placeholders replaced by `bin_name`. A relative `grub_cfg` path is
+/// resolved against `kernel_manifest`'s directory, not the process's current directory, so it
+/// keeps working when `cargo grubimage` is invoked from outside the kernel crate (e.g. via
+/// `--manifest-path` from a workspace root). Otherwise a single-entry `grub.cfg` is generated
+/// from `grub_config`'s Multiboot version, kernel cmdline, and extra modules.
+fn build_grub_cfg(
+    grub_config: &GrubConfig,
+    bin_name: &str,
+    kernel_manifest: &Path,
+) -> Result<String, CreategrubimageError> {
+    if let Some(template_path) = &grub_config.grub_cfg {
+        let template_path = match kernel_manifest.parent() {
+            Some(kernel_dir) => kernel_dir.join(template_path),
+            None => template_path.to_owned(),
+        };
+        let template =
+            fs::read_to_string(&template_path).map_err(|error| CreategrubimageError::Io {
+                message: "failed to read grub-cfg template",
+                error,
+            })?;
+        return Ok(template
+            .replace("{kernel}", bin_name)
+            .replace("{name}", bin_name));
+    }
+
+    let boot_command = match grub_config.multiboot_version {
+        MultibootVersion::One => "multiboot",
+        MultibootVersion::Two => "multiboot2",
+    };
+    let modules: String = grub_config
+        .grub_modules
+        .iter()
+        .map(|module| format!("    insmod {}\n", module))
+        .collect();
+    let cmdline = match &grub_config.kernel_cmdline {
+        Some(cmdline) if !cmdline.is_empty() => format!(" {}", cmdline),
+        _ => String::new(),
+    };
+
+    Ok(format!(
+        "set timeout=0\nset default=0\n\nmenuentry \"{name}\" {{\n{modules}    {boot_command} /boot/{name}{cmdline}\n    boot\n}}\n",
+        name = bin_name,
+        modules = modules,
+        boot_command = boot_command,
+        cmdline = cmdline,
+    ))
+}
+
+#[cfg(test)]
+mod build_grub_cfg_tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("grubimage-test-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn generates_default_single_entry_menu() {
+        let grub_config = GrubConfig::default();
+        let cfg = build_grub_cfg(&grub_config, "my-kernel", Path::new("/nonexistent/Cargo.toml"))
+            .unwrap();
+        assert!(cfg.contains("menuentry \"my-kernel\""));
+        assert!(cfg.contains("multiboot /boot/my-kernel\n"));
+    }
+
+    #[test]
+    fn honors_multiboot_version_cmdline_and_modules() {
+        let grub_config = GrubConfig {
+            multiboot_version: MultibootVersion::Two,
+            kernel_cmdline: Some("serial".to_owned()),
+            grub_modules: vec!["multiboot2".to_owned()],
+            ..GrubConfig::default()
+        };
+        let cfg = build_grub_cfg(&grub_config, "my-kernel", Path::new("/nonexistent/Cargo.toml"))
+            .unwrap();
+        assert!(cfg.contains("insmod multiboot2\n"));
+        assert!(cfg.contains("multiboot2 /boot/my-kernel serial\n"));
+    }
+
+    #[test]
+    fn resolves_relative_template_against_kernel_manifest_dir() {
+        let kernel_dir = scratch_dir("kernel-dir");
+        fs::create_dir_all(&kernel_dir).unwrap();
+        fs::write(kernel_dir.join("grub.cfg.in"), "boot {kernel} as {name}").unwrap();
+        let kernel_manifest = kernel_dir.join("Cargo.toml");
+
+        let grub_config = GrubConfig {
+            grub_cfg: Some(PathBuf::from("grub.cfg.in")),
+            ..GrubConfig::default()
+        };
+        let cfg = build_grub_cfg(&grub_config, "my-kernel", &kernel_manifest).unwrap();
+        assert_eq!(cfg, "boot my-kernel as my-kernel");
+
+        fs::remove_dir_all(&kernel_dir).unwrap();
+    }
+}
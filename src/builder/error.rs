@@ -0,0 +1,71 @@
+//! Contains the errors types returned by the `Builder` methods.
+
+use std::io;
+use thiserror::Error;
+
+/// Errors that can occur when constructing a [`super::Builder`].
+#[derive(Debug, Error)]
+pub enum BuilderError {
+    /// Failed to locate the `Cargo.toml` of the project that should be built.
+    #[error("Failed to locate Cargo.toml")]
+    LocateCargoToml(#[from] locate_cargo_manifest::LocateManifestError),
+}
+
+/// Errors that can occur when building the kernel.
+#[derive(Debug, Error)]
+pub enum BuildKernelError {
+    /// There was an I/O error while trying to run the kernel build command.
+    #[error("I/O error: {message}:\n{error}")]
+    Io {
+        /// A message describing the operation that caused the error.
+        message: &'static str,
+        /// The I/O error that occurred.
+        error: io::Error,
+    },
+    /// The kernel build command exited with an error.
+    ///
+    /// The compiler diagnostics that explain the failure were already forwarded to stderr as
+    /// they streamed in (or discarded, in quiet mode), so this variant carries no payload of
+    /// its own.
+    #[error("Kernel build failed")]
+    BuildFailed,
+    /// Failed to read or parse a `cargo_metadata::Message` from the build command's JSON output.
+    #[error("Failed to read cargo build JSON output:\n{0}")]
+    MessageStream(io::Error),
+    /// Failed to run `cargo metadata` while auto-detecting whether the kernel needs
+    /// `-Z build-std`.
+    #[error("Failed to run cargo metadata to detect build-std requirements:\n{0}")]
+    BuildStdDetection(#[from] cargo_metadata::Error),
+}
+
+/// Errors that can occur when running a built kernel executable through QEMU.
+#[derive(Debug, Error)]
+pub enum RunKernelError {
+    /// There was an I/O error while trying to spawn the run command.
+    #[error("I/O error: {message}:\n{error}")]
+    Io {
+        /// A message describing the operation that caused the error.
+        message: &'static str,
+        /// The I/O error that occurred.
+        error: io::Error,
+    },
+}
+
+/// Errors that can occur while creating the bootable disk image.
+#[derive(Debug, Error)]
+pub enum CreategrubimageError {
+    /// There was an I/O error while assembling the ISO file tree or invoking `grub-mkrescue`.
+    #[error("I/O error: {message}:\n{error}")]
+    Io {
+        /// A message describing the operation that caused the error.
+        message: &'static str,
+        /// The I/O error that occurred.
+        error: io::Error,
+    },
+    /// The `grub-mkrescue` invocation exited with an error.
+    #[error("`grub-mkrescue` failed:\n{}", String::from_utf8_lossy(stderr))]
+    GrubMkrescueFailed {
+        /// The stderr output of the failed `grub-mkrescue` invocation.
+        stderr: Vec<u8>,
+    },
+}
@@ -1,8 +1,8 @@
 //! Provides functions to build the kernel and the bootloader.
 
-use crate::config::Config;
+use crate::config::{Config, GrubConfig};
 use cargo_metadata::Metadata;
-use error::{BuildKernelError, BuilderError, CreategrubimageError};
+use error::{BuildKernelError, BuilderError, CreategrubimageError, RunKernelError};
 use std::{
     path::{Path, PathBuf},
     process,
@@ -12,6 +12,8 @@ use std::{
 mod disk_image;
 /// Contains the errors types returned by the `Builder` methods.
 pub mod error;
+/// Runs a single test binary's grubimage in QEMU and interprets the result.
+pub mod test_runner;
 
 /// Allows building the kernel and creating a bootable disk image with it.
 pub struct Builder {
@@ -35,6 +37,8 @@ pub struct Grubimage<'a> {
     pub iso_dir_path: &'a Path,
     /// Your project name / binary name
     pub bin_name: &'a str,
+    /// The GRUB bootloader setup to use for the generated disk image.
+    pub grub_config: &'a GrubConfig,
 }
 
 impl Builder {
@@ -80,53 +84,79 @@ impl Builder {
         if !quiet {
             println!("Building kernel");
         }
+        let build_command = match &config.build_command {
+            Some(build_command) => build_command.clone(),
+            None => self.default_build_command(args)?,
+        };
+        build_and_collect_artifacts(&build_command, args, quiet, false)
+    }
 
-        // try to build kernel
-        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned());
-        let mut cmd = process::Command::new(&cargo);
-        cmd.args(&config.build_command);
-        cmd.args(args);
-        if !quiet {
-            cmd.stdout(process::Stdio::inherit());
-            cmd.stderr(process::Stdio::inherit());
-        }
-        let output = cmd.output().map_err(|err| BuildKernelError::Io {
-            message: "failed to execute kernel build",
-            error: err,
-        })?;
-        if !output.status.success() {
-            return Err(BuildKernelError::BuildFailed {
-                stderr: output.stderr,
-            });
+    /// Picks the `cargo build` invocation to use when the user has not set `build-command`.
+    ///
+    /// Bare-metal kernels almost always need to build `core` and `compiler_builtins` from
+    /// source via `-Z build-std`, since the prebuilt standard library that ships with the
+    /// toolchain was built for the host, not for the kernel's (usually custom) target. We infer
+    /// whether that's the case from two signals: an explicit custom target spec on the command
+    /// line, and a `compiler_builtins` or `rustc-std-workspace-core` package anywhere in the
+    /// project's `cargo metadata` (the crates that stand in for the real ones once `build-std`
+    /// is active).
+    fn default_build_command(&mut self, args: &[String]) -> Result<Vec<String>, BuildKernelError> {
+        let mut command = vec!["build".to_owned()];
+        if self.wants_build_std(args)? {
+            command.push("-Z".to_owned());
+            command.push("build-std=core,compiler_builtins".to_owned());
+            command.push("-Z".to_owned());
+            command.push("build-std-features=compiler-builtins-mem".to_owned());
         }
+        Ok(command)
+    }
 
-        // Retrieve binary paths
-        let mut cmd = process::Command::new(cargo);
-        cmd.args(&config.build_command);
-        cmd.args(args);
-        cmd.arg("--message-format").arg("json");
-        let output = cmd.output().map_err(|err| BuildKernelError::Io {
-            message: "failed to execute kernel build with json output",
-            error: err,
-        })?;
-        if !output.status.success() {
-            return Err(BuildKernelError::BuildFailed {
-                stderr: output.stderr,
-            });
+    /// Returns whether the kernel being built needs `-Z build-std` to compile.
+    fn wants_build_std(&mut self, args: &[String]) -> Result<bool, BuildKernelError> {
+        if has_custom_target(args) {
+            return Ok(true);
         }
-        let mut executables = Vec::new();
-        for line in String::from_utf8(output.stdout)
-            .map_err(BuildKernelError::BuildJsonOutputInvalidUtf8)?
-            .lines()
-        {
-            let mut artifact =
-                json::parse(line).map_err(BuildKernelError::BuildJsonOutputInvalidJson)?;
-            if let Some(executable) = artifact["executable"].take_string() {
-                executables.push(PathBuf::from(executable));
-            }
+        Ok(self.project_metadata()?.packages.iter().any(|package| {
+            package.name == "compiler_builtins" || package.name == "rustc-std-workspace-core"
+        }))
+    }
+
+    /// Builds every test binary of the kernel crate via `cargo test --no-run`.
+    ///
+    /// Resolves the build command the same way [`Self::build_kernel`] does (honoring
+    /// `config.build_command`, falling back to build-std auto-detection), so a custom
+    /// `build-command` or an auto-detected `-Z build-std` applies to test binaries too.
+    ///
+    /// Returns a list of paths to all built test executables, analogous to [`Self::build_kernel`].
+    pub fn build_test_kernels(
+        &mut self,
+        args: &[String],
+        config: &Config,
+        quiet: bool,
+    ) -> Result<Vec<PathBuf>, BuildKernelError> {
+        if !quiet {
+            println!("Building kernel tests");
         }
+        let build_command = match &config.build_command {
+            Some(build_command) => build_command.clone(),
+            None => self.default_build_command(args)?,
+        };
+        build_and_collect_artifacts(&to_test_command(&build_command), args, quiet, true)
+    }
 
-        Ok(executables)
+    /// Runs a test binary's grubimage in QEMU, once per configured `package.metadata.grubimage`
+    /// profile (or once, against `run_command`, if no profiles are configured).
+    ///
+    /// Honors `test_timeout`, `test_success_exit_code`, and `test_no_reboot` (overridden
+    /// per-profile where applicable). See [`test_runner::run_test_profiles`] for the full
+    /// semantics.
+    pub fn run_test_image(
+        &self,
+        image_path: &Path,
+        config: &Config,
+        extra_args: &[String],
+    ) -> Result<Vec<(String, test_runner::TestStatus)>, RunKernelError> {
+        test_runner::run_test_profiles(image_path, config, extra_args)
     }
 
     /// Creates a grubimage by combining the given kernel binary with the bootloader.
@@ -140,6 +170,8 @@ impl Builder {
             args.iso_dir_path,
             args.bin_path,
             args.bin_name,
+            args.grub_config,
+            args.kernel_manifest,
         )?;
 
         Ok(())
@@ -167,3 +199,219 @@ impl Builder {
         Ok(self.project_metadata.get_or_insert(metadata))
     }
 }
+
+/// Returns whether `args` passes a custom target spec (a `--target` pointing at a `.json` file)
+/// rather than a builtin target triple.
+fn has_custom_target(args: &[String]) -> bool {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let target = if let Some(value) = arg.strip_prefix("--target=") {
+            Some(value)
+        } else if arg == "--target" {
+            args.next().map(String::as_str)
+        } else {
+            None
+        };
+        if let Some(target) = target {
+            return target.ends_with(".json");
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod has_custom_target_tests {
+    use super::has_custom_target;
+
+    #[test]
+    fn detects_target_json_in_either_arg_form() {
+        assert!(has_custom_target(&["--target".to_owned(), "foo.json".to_owned()]));
+        assert!(has_custom_target(&["--target=foo.json".to_owned()]));
+    }
+
+    #[test]
+    fn ignores_builtin_target_triples() {
+        assert!(!has_custom_target(&[
+            "--target".to_owned(),
+            "x86_64-unknown-linux-gnu".to_owned()
+        ]));
+        assert!(!has_custom_target(&["--target=x86_64-unknown-linux-gnu".to_owned()]));
+    }
+
+    #[test]
+    fn ignores_unrelated_args() {
+        assert!(!has_custom_target(&["--release".to_owned()]));
+        assert!(!has_custom_target(&[]));
+    }
+}
+
+/// Runs the given bootable disk image by spawning `config.run_command`.
+///
+/// Every `"{}"` placeholder in `config.run_command` is replaced with `image_path`, and
+/// `extra_args` is appended after the configured arguments.
+///
+/// This doesn't need a [`Builder`]: unlike `build_kernel`/`create_grubimage`, running an already
+/// built image has no cargo-metadata state to cache.
+pub fn run_image(
+    image_path: &Path,
+    config: &Config,
+    extra_args: &[String],
+) -> Result<process::ExitStatus, RunKernelError> {
+    run_once(&config.run_command, image_path, extra_args)
+}
+
+/// Runs the given bootable disk image once per configured `package.metadata.grubimage` profile
+/// (or once, against `config.run_command`, if no profiles are configured).
+///
+/// Returns the name and exit status of every profile that ran, in configured order. Unlike
+/// [`Builder::run_test_image`], there is no timeout or `test_success_exit_code` mapping applied:
+/// this is the plain `cargo grubimage run` path, not the test harness.
+pub fn run_image_profiles(
+    image_path: &Path,
+    config: &Config,
+    extra_args: &[String],
+) -> Result<Vec<(String, process::ExitStatus)>, RunKernelError> {
+    if config.profiles.is_empty() {
+        let status = run_image(image_path, config, extra_args)?;
+        return Ok(vec![("default".to_owned(), status)]);
+    }
+
+    let mut results = Vec::new();
+    for profile in &config.profiles {
+        let profile_args: &[String] = profile.run_args.as_deref().unwrap_or(extra_args);
+        let status = run_once(&profile.run_command, image_path, profile_args)?;
+        results.push((profile.name.clone(), status));
+    }
+    Ok(results)
+}
+
+/// Spawns a `run_command` (with every `"{}"` placeholder replaced by `image_path`, and
+/// `extra_args` appended after the configured arguments) and blocks until it exits.
+fn run_once(
+    run_command: &[String],
+    image_path: &Path,
+    extra_args: &[String],
+) -> Result<process::ExitStatus, RunKernelError> {
+    let replaced: Vec<String> = run_command
+        .iter()
+        .map(|arg| arg.replace("{}", &image_path.display().to_string()))
+        .collect();
+    let (program, command_args) = replaced.split_first().ok_or_else(|| RunKernelError::Io {
+        message: "run-command must not be empty",
+        error: std::io::Error::from(std::io::ErrorKind::InvalidInput),
+    })?;
+
+    let mut cmd = process::Command::new(program);
+    cmd.args(command_args);
+    cmd.args(extra_args);
+    cmd.status().map_err(|error| RunKernelError::Io {
+        message: "failed to execute run command",
+        error,
+    })
+}
+
+/// Rewrites a resolved `cargo build` command (as returned by `config.build_command` or
+/// [`Builder::default_build_command`]) into the equivalent `cargo test --no-run` invocation,
+/// preserving any extra flags (e.g. an auto-detected `-Z build-std=...`).
+fn to_test_command(build_command: &[String]) -> Vec<String> {
+    let mut command = vec!["test".to_owned(), "--no-run".to_owned()];
+    command.extend(build_command.iter().skip(1).cloned());
+    command
+}
+
+#[cfg(test)]
+mod to_test_command_tests {
+    use super::to_test_command;
+
+    #[test]
+    fn plain_build_becomes_test_no_run() {
+        assert_eq!(
+            to_test_command(&["build".to_owned()]),
+            vec!["test".to_owned(), "--no-run".to_owned()]
+        );
+    }
+
+    #[test]
+    fn preserves_extra_flags_after_the_subcommand() {
+        let build_command = vec![
+            "build".to_owned(),
+            "-Z".to_owned(),
+            "build-std=core,compiler_builtins".to_owned(),
+            "-Z".to_owned(),
+            "build-std-features=compiler-builtins-mem".to_owned(),
+        ];
+        assert_eq!(
+            to_test_command(&build_command),
+            vec![
+                "test".to_owned(),
+                "--no-run".to_owned(),
+                "-Z".to_owned(),
+                "build-std=core,compiler_builtins".to_owned(),
+                "-Z".to_owned(),
+                "build-std-features=compiler-builtins-mem".to_owned(),
+            ]
+        );
+    }
+}
+
+/// Runs `cargo <subcommand_args> <args> --message-format json-render-diagnostics` once, and
+/// collects the executables produced by the build. If `tests_only` is set, only executables
+/// built with the test profile are collected, so e.g. the kernel binary that `cargo test`
+/// builds for integration tests is left out.
+///
+/// Compiler diagnostics (warnings and errors) are forwarded to stderr as they stream in, so the
+/// user sees them exactly as they would from a plain `cargo build` even though the build only
+/// talks to us in JSON. If `quiet` is set, diagnostics are suppressed and cargo's own stderr
+/// (status lines like "Compiling foo v0.1.0") is discarded instead of inherited, matching the
+/// `-q`/`--quiet` contract.
+fn build_and_collect_artifacts(
+    subcommand_args: &[String],
+    args: &[String],
+    quiet: bool,
+    tests_only: bool,
+) -> Result<Vec<PathBuf>, BuildKernelError> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned());
+    let mut cmd = process::Command::new(cargo);
+    cmd.args(subcommand_args);
+    cmd.args(args);
+    cmd.arg("--message-format").arg("json-render-diagnostics");
+    cmd.stdout(process::Stdio::piped());
+    if quiet {
+        cmd.stderr(process::Stdio::null());
+    }
+
+    let mut child = cmd.spawn().map_err(|error| BuildKernelError::Io {
+        message: "failed to execute kernel build",
+        error,
+    })?;
+    let stdout = child.stdout.take().expect("stdout was piped");
+
+    let mut executables = Vec::new();
+    for message in cargo_metadata::Message::parse_stream(std::io::BufReader::new(stdout)) {
+        match message.map_err(BuildKernelError::MessageStream)? {
+            cargo_metadata::Message::CompilerArtifact(artifact)
+                if !tests_only || artifact.profile.test =>
+            {
+                if let Some(executable) = artifact.executable {
+                    executables.push(executable.into_std_path_buf());
+                }
+            }
+            cargo_metadata::Message::CompilerMessage(message) if !quiet => {
+                if let Some(rendered) = message.message.rendered {
+                    eprint!("{}", rendered);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let status = child.wait().map_err(|error| BuildKernelError::Io {
+        message: "failed to wait for kernel build",
+        error,
+    })?;
+    if !status.success() {
+        return Err(BuildKernelError::BuildFailed);
+    }
+
+    Ok(executables)
+}
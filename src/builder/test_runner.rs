@@ -0,0 +1,199 @@
+//! Runs a single test binary's grubimage in QEMU (possibly against several machine profiles)
+//! and interprets the result.
+
+use super::error::RunKernelError;
+use crate::config::Config;
+use std::{
+    io::{BufRead, BufReader, Read},
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The interval at which the run loop polls the child for exit and checks the timeout.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The label used for the single implicit profile when `package.metadata.grubimage.profiles`
+/// is empty.
+const DEFAULT_PROFILE: &str = "default";
+
+/// The outcome of running a single test binary inside QEMU.
+#[derive(Debug)]
+pub enum TestStatus {
+    /// The test exited with a status considered successful.
+    Success,
+    /// The test exited with a status considered a failure.
+    Failed(ExitStatus),
+    /// The test did not exit within the configured timeout and was killed.
+    TimedOut,
+}
+
+/// Runs a test binary's grubimage once per configured QEMU profile (or once, against
+/// `config.run_command`, if no profiles are configured).
+///
+/// Returns the name and [`TestStatus`] of every profile that ran, in configured order.
+pub fn run_test_profiles(
+    image_path: &Path,
+    config: &Config,
+    extra_args: &[String],
+) -> Result<Vec<(String, TestStatus)>, RunKernelError> {
+    if config.profiles.is_empty() {
+        let status = run_test(
+            image_path,
+            &config.run_command,
+            extra_args,
+            config.test_timeout,
+            config.test_no_reboot,
+            config.test_success_exit_code,
+            DEFAULT_PROFILE,
+        )?;
+        return Ok(vec![(DEFAULT_PROFILE.to_owned(), status)]);
+    }
+
+    let mut results = Vec::new();
+    for profile in &config.profiles {
+        let profile_args: &[String] = profile.run_args.as_deref().unwrap_or(extra_args);
+        let timeout = profile.test_timeout.unwrap_or(config.test_timeout);
+        let status = run_test(
+            image_path,
+            &profile.run_command,
+            profile_args,
+            timeout,
+            config.test_no_reboot,
+            config.test_success_exit_code,
+            &profile.name,
+        )?;
+        results.push((profile.name.clone(), status));
+    }
+    Ok(results)
+}
+
+/// Spawns `run_command` against `image_path`, waits for it to exit (or for `test_timeout`
+/// seconds to elapse), and maps the result to a [`TestStatus`].
+///
+/// The child's stdout and stderr are streamed live on background threads, each line prefixed
+/// with `[label]`, so the guest's serial output is visible (and attributable to its profile)
+/// while the test runs. When `config.test_success_exit_code` is set, QEMU's isa-debug-exit
+/// convention is applied: a guest exit code `n` is reported by QEMU as the process exit status
+/// `(n << 1) | 1`, and only that exact status counts as success. Otherwise a plain zero exit
+/// status counts as success.
+fn run_test(
+    image_path: &Path,
+    run_command: &[String],
+    extra_args: &[String],
+    test_timeout: u32,
+    test_no_reboot: bool,
+    test_success_exit_code: Option<i32>,
+    label: &str,
+) -> Result<TestStatus, RunKernelError> {
+    let replaced: Vec<String> = run_command
+        .iter()
+        .map(|arg| arg.replace("{}", &image_path.display().to_string()))
+        .collect();
+    let (program, command_args) = replaced.split_first().ok_or_else(|| RunKernelError::Io {
+        message: "run-command must not be empty",
+        error: std::io::Error::from(std::io::ErrorKind::InvalidInput),
+    })?;
+
+    let mut cmd = Command::new(program);
+    cmd.args(command_args);
+    cmd.args(extra_args);
+    if test_no_reboot {
+        cmd.arg("-no-reboot");
+    }
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+
+    let mut child: Child = cmd.spawn().map_err(|error| RunKernelError::Io {
+        message: "failed to spawn run command",
+        error,
+    })?;
+
+    let stdout = child.stdout.take().expect("stdout was piped");
+    let stderr = child.stderr.take().expect("stderr was piped");
+    let stdout_label = label.to_owned();
+    let stderr_label = label.to_owned();
+    let stdout_thread = thread::spawn(move || stream_lines(stdout, &stdout_label, false));
+    let stderr_thread = thread::spawn(move || stream_lines(stderr, &stderr_label, true));
+
+    let timeout = Duration::from_secs(u64::from(test_timeout));
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|error| RunKernelError::Io {
+            message: "failed to poll run command",
+            error,
+        })? {
+            break Some(status);
+        }
+        if start.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    // The child has exited (or been killed), so the streaming threads will see EOF shortly.
+    let _ = stdout_thread.join();
+    let _ = stderr_thread.join();
+
+    let status = match status {
+        Some(status) => status,
+        None => return Ok(TestStatus::TimedOut),
+    };
+
+    Ok(if is_test_success(status.code(), test_success_exit_code) {
+        TestStatus::Success
+    } else {
+        TestStatus::Failed(status)
+    })
+}
+
+/// Returns whether an exited test process's status code counts as success.
+///
+/// When `test_success_exit_code` is configured, QEMU's isa-debug-exit convention applies: a
+/// guest exit code `n` is reported by QEMU as the process exit status `(n << 1) | 1`, and only
+/// that exact status counts as success. Otherwise a plain zero exit status counts as success.
+fn is_test_success(status_code: Option<i32>, test_success_exit_code: Option<i32>) -> bool {
+    match test_success_exit_code {
+        Some(exit_code) => status_code == Some((exit_code << 1) | 1),
+        None => status_code == Some(0),
+    }
+}
+
+/// Reads `reader` line by line, forwarding each line (prefixed with `[label]`) to stdout or
+/// stderr as it arrives.
+fn stream_lines(reader: impl Read, label: &str, is_stderr: bool) {
+    for line in BufReader::new(reader).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if is_stderr {
+            eprintln!("[{}] {}", label, line);
+        } else {
+            println!("[{}] {}", label, line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_test_success;
+
+    #[test]
+    fn plain_zero_exit_is_success_without_configured_exit_code() {
+        assert!(is_test_success(Some(0), None));
+        assert!(!is_test_success(Some(1), None));
+        assert!(!is_test_success(None, None));
+    }
+
+    #[test]
+    fn isa_debug_exit_mapping_requires_exact_match() {
+        // QEMU reports guest exit code 3 as process exit status (3 << 1) | 1 == 7.
+        assert!(is_test_success(Some(7), Some(3)));
+        assert!(!is_test_success(Some(0), Some(3)));
+        assert!(!is_test_success(Some(5), Some(3)));
+    }
+}
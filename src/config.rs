@@ -1,7 +1,7 @@
 //! Parses the `package.metadata.grubimage` configuration table
 
 use anyhow::{anyhow, Context, Result};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use toml::Value;
 
 /// Represents the `package.metadata.grubimage` configuration table
@@ -14,8 +14,9 @@ use toml::Value;
 pub struct Config {
     /// The cargo subcommand that is used for building the kernel for `cargo grubimage`.
     ///
-    /// Defaults to `build`.
-    pub build_command: Vec<String>,
+    /// Defaults to `build`, with `-Z build-std` automatically injected when the kernel appears
+    /// to need it. Set this explicitly to take full control and disable auto-detection.
+    pub build_command: Option<Vec<String>>,
     /// The run command that is invoked on `grubimage run` or `grubimage runner`
     ///
     /// The substring "{}" will be replaced with the path to the bootable disk image.
@@ -37,6 +38,69 @@ pub struct Config {
     ///
     /// Defaults to `true`
     pub test_no_reboot: bool,
+    /// The GRUB bootloader setup (`grub.cfg` template, Multiboot version, kernel cmdline, and
+    /// extra modules) used when assembling the disk image.
+    pub grub: GrubConfig,
+    /// Named QEMU machine profiles to run `grubimage run`, `grubimage test`, and test binaries
+    /// under `grubimage runner` against.
+    ///
+    /// When empty (the default), `run_command`/`test_timeout` are used directly. When
+    /// non-empty, the disk image is booted once per profile (e.g. an i386 BIOS profile and a
+    /// UEFI/OVMF profile), and the overall command fails if any profile fails.
+    pub profiles: Vec<Profile>,
+}
+
+/// A named QEMU machine profile, as configured under
+/// `package.metadata.grubimage.profiles.<name>`.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Profile {
+    /// The name of the profile, used to label its output and report its pass/fail status.
+    pub name: String,
+    /// The run command for this profile, overriding the top-level `run-command`.
+    ///
+    /// The substring "{}" will be replaced with the path to the bootable disk image.
+    pub run_command: Vec<String>,
+    /// Additional arguments passed to the runner for this profile, overriding the top-level
+    /// `run-args`/`test-args`.
+    pub run_args: Option<Vec<String>>,
+    /// The timeout in seconds for this profile, overriding the top-level `test-timeout`.
+    pub test_timeout: Option<u32>,
+}
+
+/// Represents the GRUB-specific part of the `package.metadata.grubimage` configuration table.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct GrubConfig {
+    /// A user-supplied `grub.cfg` template.
+    ///
+    /// The placeholders `{kernel}` and `{name}` are replaced with the kernel's on-disk file
+    /// name. When not set, a single-entry `grub.cfg` is generated from `kernel_cmdline`,
+    /// `multiboot_version`, and `grub_modules`.
+    pub grub_cfg: Option<PathBuf>,
+    /// A string appended to the `multiboot`/`multiboot2` line of the generated `grub.cfg`.
+    ///
+    /// Ignored when `grub_cfg` is set.
+    pub kernel_cmdline: Option<String>,
+    /// The Multiboot version to boot the kernel as.
+    ///
+    /// Selects between the `multiboot` (version 1) and `multiboot2` (version 2) GRUB commands
+    /// in the generated `grub.cfg`. Ignored when `grub_cfg` is set. Defaults to version 1.
+    pub multiboot_version: MultibootVersion,
+    /// Extra GRUB modules to `insmod` before loading the kernel.
+    ///
+    /// Ignored when `grub_cfg` is set.
+    pub grub_modules: Vec<String>,
+}
+
+/// The Multiboot specification version used to boot the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MultibootVersion {
+    /// Boot the kernel via the `multiboot` GRUB command.
+    #[default]
+    One,
+    /// Boot the kernel via the `multiboot2` GRUB command.
+    Two,
 }
 
 /// Reads the configuration from a `package.metadata.grubimage` in the given Cargo.toml.
@@ -98,6 +162,30 @@ fn read_config_inner(manifest_path: &Path) -> Result<Config> {
             ("test-no-reboot", Value::Boolean(no_reboot)) => {
                 config.test_no_reboot = Some(no_reboot);
             }
+            ("grub-cfg", Value::String(path)) => {
+                config.grub_cfg = Some(PathBuf::from(path));
+            }
+            ("kernel-cmdline", Value::String(cmdline)) => {
+                config.kernel_cmdline = Some(cmdline);
+            }
+            ("multiboot-version", Value::Integer(1)) => {
+                config.multiboot_version = Some(MultibootVersion::One);
+            }
+            ("multiboot-version", Value::Integer(2)) => {
+                config.multiboot_version = Some(MultibootVersion::Two);
+            }
+            ("multiboot-version", Value::Integer(version)) => {
+                return Err(anyhow!(
+                    "multiboot-version must be 1 or 2, found `{}`",
+                    version
+                ))
+            }
+            ("grub-modules", Value::Array(array)) => {
+                config.grub_modules = Some(parse_string_array(array, "grub-modules")?);
+            }
+            ("profiles", Value::Table(table)) => {
+                config.profiles = Some(parse_profiles(table)?);
+            }
             (key, value) => {
                 return Err(anyhow!(
                     "unexpected `package.metadata.grubimage` \
@@ -111,6 +199,50 @@ fn read_config_inner(manifest_path: &Path) -> Result<Config> {
     Ok(config.into())
 }
 
+fn parse_profiles(table: toml::value::Table) -> Result<Vec<Profile>> {
+    let mut profiles = Vec::new();
+    for (name, value) in table {
+        let profile_table = value
+            .as_table()
+            .ok_or_else(|| anyhow!("profile `{}` must be a table", name))?;
+
+        let mut run_command = None;
+        let mut run_args = None;
+        let mut test_timeout = None;
+        for (key, value) in profile_table {
+            match (key.as_str(), value.clone()) {
+                ("run-command", Value::Array(array)) => {
+                    run_command = Some(parse_string_array(array, "profiles.*.run-command")?);
+                }
+                ("run-args", Value::Array(array)) => {
+                    run_args = Some(parse_string_array(array, "profiles.*.run-args")?);
+                }
+                ("test-timeout", Value::Integer(timeout)) if timeout.is_negative() => {
+                    return Err(anyhow!("profiles.*.test-timeout must not be negative"))
+                }
+                ("test-timeout", Value::Integer(timeout)) => {
+                    test_timeout = Some(timeout as u32);
+                }
+                (key, value) => return Err(anyhow!(
+                    "unexpected `package.metadata.grubimage.profiles.{}` key `{}` with value `{}`",
+                    name,
+                    key,
+                    value
+                )),
+            }
+        }
+
+        profiles.push(Profile {
+            name: name.clone(),
+            run_command: run_command
+                .ok_or_else(|| anyhow!("profile `{}` is missing `run-command`", name))?,
+            run_args,
+            test_timeout,
+        });
+    }
+    Ok(profiles)
+}
+
 fn parse_string_array(array: Vec<Value>, prop_name: &str) -> Result<Vec<String>> {
     let mut parsed = Vec::new();
     for value in array {
@@ -131,24 +263,32 @@ struct ConfigBuilder {
     test_timeout: Option<u32>,
     test_success_exit_code: Option<i32>,
     test_no_reboot: Option<bool>,
+    grub_cfg: Option<PathBuf>,
+    kernel_cmdline: Option<String>,
+    multiboot_version: Option<MultibootVersion>,
+    grub_modules: Option<Vec<String>>,
+    profiles: Option<Vec<Profile>>,
 }
 
 impl From<ConfigBuilder> for Config {
     fn from(s: ConfigBuilder) -> Config {
         Config {
-            build_command: s.build_command.unwrap_or_else(|| vec!["build".into()]),
-            run_command: s.run_command.unwrap_or_else(|| {
-                vec![
-                    "qemu-system-i386".into(),
-                    "-cdrom".into(),
-                    "{}".into(),
-                ]
-            }),
+            build_command: s.build_command,
+            run_command: s
+                .run_command
+                .unwrap_or_else(|| vec!["qemu-system-i386".into(), "-cdrom".into(), "{}".into()]),
             run_args: s.run_args,
             test_args: s.test_args,
             test_timeout: s.test_timeout.unwrap_or(60 * 5),
             test_success_exit_code: s.test_success_exit_code,
             test_no_reboot: s.test_no_reboot.unwrap_or(true),
+            grub: GrubConfig {
+                grub_cfg: s.grub_cfg,
+                kernel_cmdline: s.kernel_cmdline,
+                multiboot_version: s.multiboot_version.unwrap_or_default(),
+                grub_modules: s.grub_modules.unwrap_or_default(),
+            },
+            profiles: s.profiles.unwrap_or_default(),
         }
     }
 }
@@ -0,0 +1,38 @@
+//! Prints help and version information for `cargo grubimage`.
+
+/// Prints the crate name and version.
+pub fn print_version() {
+    println!("cargo-grubimage {}", env!("CARGO_PKG_VERSION"));
+}
+
+/// Prints usage information for `cargo grubimage`.
+pub fn print_cargo_grubimage_help() {
+    println!(
+        r#"cargo-grubimage {}
+Creates a bootable GRUB disk image from a Rust kernel.
+
+USAGE:
+    cargo grubimage [build] [CARGO BUILD OPTIONS]
+    cargo grubimage run [CARGO BUILD OPTIONS]
+    cargo grubimage runner <EXECUTABLE>
+    cargo grubimage test [CARGO BUILD OPTIONS]
+
+SUBCOMMANDS:
+    build     Builds the kernel and wraps it in a bootable GRUB disk image (default)
+    run       Builds the kernel, wraps it in a disk image, and runs it via `run-command`
+    runner    Wraps a single already-built kernel executable in a disk image and runs it;
+              meant to be used as a `target.*.runner` entry point
+    test      Builds and runs every test binary of the kernel crate
+
+OPTIONS:
+    --release            Build the kernel in release mode
+    --manifest-path PATH Path to the Cargo.toml of the kernel
+    -q, --quiet          Suppress build output
+    -h, --help           Prints this help message
+    -V, --version        Prints version information
+
+All other options are forwarded to the underlying `cargo build` invocation.
+"#,
+        env!("CARGO_PKG_VERSION")
+    );
+}
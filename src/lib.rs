@@ -0,0 +1,7 @@
+//! Library side of `cargo grubimage`, a tool for turning a Rust kernel into a bootable GRUB
+//! disk image.
+
+pub mod args;
+pub mod builder;
+pub mod config;
+pub mod help;